@@ -1,9 +1,429 @@
-use std::path::Path;
+//! Linux-specific child setup: mounts, `pivot_root`, mount propagation,
+//! capability dropping, seccomp filtering and the subreaper flag.
+//!
+//! This module assumes `Command`'s `config: Config` field carries
+//! `mounts: Vec<MountSpec>`, `propagation: Option<(Propagation, bool)>`,
+//! `capabilities: Option<Vec<i32>>`, `no_new_privs: bool`,
+//! `seccomp: Option<BpfProgram>` and `child_subreaper: bool` alongside
+//! the namespace/signal fields the rest of the crate already defines;
+//! add them to `Config` if they aren't there yet. It also depends
+//! directly on the `libc` crate (for raw syscalls and `prctl`/`mount`
+//! constants) in addition to `nix`, so `Cargo.toml` needs a `libc`
+//! dependency alongside the existing `nix` one.
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use libc;
 use nix::sys::signal::{SigNum};
 use nix::sched as consts;
 
 use {Command, Namespace};
 
+/// Converts `path` to a NUL-terminated `CString`, suitable for handing
+/// to a raw syscall. Done once in the parent, at builder-call time, so
+/// the queued `CString` can be dereferenced in the forked child without
+/// any allocation (the child, between `clone` and `execve`, must be
+/// async-signal-safe, and `malloc` is not).
+///
+/// # Panics
+///
+/// Panics if `path` contains a NUL byte.
+fn path_to_cstring(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes())
+        .expect("path must not contain a NUL byte")
+}
+
+/// Returns true if `path` looks like the root of a mount, i.e. its
+/// device differs from its parent's. `pivot_root(2)` requires
+/// `new_root` to be a mount point. Uses raw `stat(2)` calls and a
+/// stack buffer for the parent path, so it stays allocation-free for
+/// use between `clone` and `execve`.
+fn is_mount_point(path: &CString) -> bool {
+    unsafe {
+        let mut st: libc::stat = mem::zeroed();
+        if libc::stat(path.as_ptr(), &mut st) != 0 {
+            return false;
+        }
+        let bytes = path.as_bytes();
+        let parent_len = match bytes.iter().rposition(|&b| b == b'/') {
+            Some(0) => 1,
+            Some(i) => i,
+            None => return true,
+        };
+        let mut buf = [0u8; libc::PATH_MAX as usize];
+        if parent_len >= buf.len() {
+            return false;
+        }
+        buf[..parent_len].copy_from_slice(&bytes[..parent_len]);
+        buf[parent_len] = 0;
+        let mut parent_st: libc::stat = mem::zeroed();
+        if libc::stat(buf.as_ptr() as *const libc::c_char, &mut parent_st) != 0 {
+            return false;
+        }
+        parent_st.st_dev != st.st_dev
+    }
+}
+
+const ROOT: &'static [u8] = b"/\0";
+const DOT: &'static [u8] = b".\0";
+
+/// Applies a `PivotRoot` spec in the child, switching to the new root
+/// filesystem either the classic way (`put_old` supplied by the
+/// caller) or via the `pivot_root(".", ".")` dance. Raw syscalls only,
+/// over paths that were already converted to `CString` in the parent,
+/// so nothing here allocates.
+fn apply_pivot_root(spec: &PivotRoot) -> io::Result<()> {
+    let root = unsafe { CStr::from_bytes_with_nul_unchecked(ROOT) };
+    let dot = unsafe { CStr::from_bytes_with_nul_unchecked(DOT) };
+    match *spec {
+        PivotRoot::WithPutOld { ref new_root, ref put_old, unmount } => {
+            let rc = unsafe {
+                libc::syscall(libc::SYS_pivot_root, new_root.as_ptr(), put_old.as_ptr())
+            };
+            if rc != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if unmount {
+                if unsafe { libc::umount2(put_old.as_ptr(), libc::MNT_DETACH) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        PivotRoot::Dance { ref new_root, unmount } => {
+            if !is_mount_point(new_root) {
+                let rc = unsafe {
+                    libc::mount(new_root.as_ptr(), new_root.as_ptr(), ptr::null(),
+                                (libc::MS_BIND | libc::MS_REC) as libc::c_ulong,
+                                ptr::null())
+                };
+                if rc != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            let old_root_fd = unsafe {
+                libc::open(root.as_ptr(), libc::O_DIRECTORY | libc::O_RDONLY | libc::O_CLOEXEC)
+            };
+            if old_root_fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let new_root_fd = unsafe {
+                libc::open(new_root.as_ptr(),
+                           libc::O_DIRECTORY | libc::O_RDONLY | libc::O_CLOEXEC)
+            };
+            if new_root_fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if unsafe { libc::fchdir(new_root_fd) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let rc = unsafe {
+                libc::syscall(libc::SYS_pivot_root, dot.as_ptr(), dot.as_ptr())
+            };
+            if rc != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if unsafe { libc::fchdir(old_root_fd) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let rc = unsafe {
+                libc::mount(ptr::null(), dot.as_ptr(), ptr::null(),
+                            (libc::MS_SLAVE | libc::MS_REC) as libc::c_ulong,
+                            ptr::null())
+            };
+            if rc != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if unmount {
+                if unsafe { libc::umount2(dot.as_ptr(), libc::MNT_DETACH) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if unsafe { libc::chdir(root.as_ptr()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            unsafe {
+                libc::close(old_root_fd);
+                libc::close(new_root_fd);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets mount propagation on `/` in the child via
+/// `mount("", "/", "", prop[|MS_REC], NULL)`. A raw syscall against a
+/// static path, so it stays allocation-free.
+fn apply_propagation(prop: Propagation, recursive: bool) -> io::Result<()> {
+    let root = unsafe { CStr::from_bytes_with_nul_unchecked(ROOT) };
+    let mut flags = match prop {
+        Propagation::Private => libc::MS_PRIVATE,
+        Propagation::Slave => libc::MS_SLAVE,
+        Propagation::Shared => libc::MS_SHARED,
+        Propagation::Unbindable => libc::MS_UNBINDABLE,
+    };
+    if recursive {
+        flags |= libc::MS_REC;
+    }
+    let rc = unsafe {
+        libc::mount(ptr::null(), root.as_ptr(), ptr::null(),
+                    flags as libc::c_ulong, ptr::null())
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Picks the propagation to apply for this command: whatever was set
+/// explicitly via `set_mount_propagation`, or, failing that, recursive
+/// `Slave` if `pivot_root`/`pivot_root_to` was requested with
+/// `unmount: true` (the safe default the man pages describe), or
+/// nothing at all otherwise.
+fn select_propagation(cmd: &Command) -> Option<(Propagation, bool)> {
+    if let Some(prop) = cmd.config.propagation {
+        return Some(prop);
+    }
+    let wants_default = match cmd.pivot_root {
+        Some(PivotRoot::WithPutOld { unmount, .. }) => unmount,
+        Some(PivotRoot::Dance { unmount, .. }) => unmount,
+        None => false,
+    };
+    if wants_default {
+        Some((Propagation::Slave, true))
+    } else {
+        None
+    }
+}
+
+/// Performs each queued `MountSpec` in order, in the child, via raw
+/// `mount(2)` calls over paths that were already converted to
+/// `CString` in the parent, so nothing here allocates.
+fn apply_mounts(mounts: &[MountSpec]) -> io::Result<()> {
+    let proc_fs = unsafe { CStr::from_bytes_with_nul_unchecked(b"proc\0") };
+    let tmpfs_fs = unsafe { CStr::from_bytes_with_nul_unchecked(b"tmpfs\0") };
+    for spec in mounts {
+        let rc = match *spec {
+            MountSpec::Bind { ref src, ref dest, recursive } => {
+                let mut flags = libc::MS_BIND;
+                if recursive {
+                    flags |= libc::MS_REC;
+                }
+                unsafe {
+                    libc::mount(src.as_ptr(), dest.as_ptr(), ptr::null(),
+                                flags as libc::c_ulong, ptr::null())
+                }
+            }
+            MountSpec::Proc { ref dest } => unsafe {
+                libc::mount(proc_fs.as_ptr(), dest.as_ptr(), proc_fs.as_ptr(),
+                            0, ptr::null())
+            },
+            MountSpec::Tmpfs { ref dest, ref options } => unsafe {
+                libc::mount(tmpfs_fs.as_ptr(), dest.as_ptr(), tmpfs_fs.as_ptr(),
+                            0, options.as_ptr() as *const libc::c_void)
+            },
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// The two supported ways of switching to a new root filesystem via
+/// `pivot_root(2)`, set up by `Command::pivot_root` and
+/// `Command::pivot_root_to` respectively. `pub(crate)` because the
+/// crate's exec machinery (which calls `Command::setup_child` in the
+/// forked child) matches on it directly.
+#[derive(Debug, Clone)]
+pub(crate) enum PivotRoot {
+    /// The classic mode: `new_root` and `put_old` are both supplied by
+    /// the caller, and `new_root` must be a prefix of `put_old`.
+    WithPutOld {
+        new_root: CString,
+        put_old: CString,
+        unmount: bool,
+    },
+    /// The `pivot_root(".", ".")` dance used by LXC and other
+    /// container runtimes: no `put_old` directory is needed because
+    /// the old root is stacked onto `new_root` at the current
+    /// directory and then, if `unmount` is set, detached in place.
+    Dance {
+        new_root: CString,
+        unmount: bool,
+    },
+}
+
+/// A single mount operation to perform in the child, queued up by
+/// `Command::add_mount` (or one of the `bind_mount`/`mount_proc`/
+/// `mount_tmpfs` shortcuts) and applied after namespaces are unshared
+/// but before `pivot_root`/`chroot_dir` switch the root.
+#[derive(Debug, Clone)]
+pub enum MountSpec {
+    /// Bind mount `src` onto `dest`, using `MS_REC` if `recursive` is
+    /// set so that mounts nested under `src` come along too.
+    Bind {
+        src: CString,
+        dest: CString,
+        recursive: bool,
+    },
+    /// Mount a fresh `procfs` at `dest`.
+    Proc {
+        dest: CString,
+    },
+    /// Mount a `tmpfs` at `dest`, with `options` passed verbatim as the
+    /// mount data string (e.g. `"size=64m,mode=0755"`).
+    Tmpfs {
+        dest: CString,
+        options: CString,
+    },
+}
+
+/// Mount propagation type to apply with `Command::set_mount_propagation`,
+/// mirroring the `MS_PRIVATE`/`MS_SLAVE`/`MS_SHARED`/`MS_UNBINDABLE`
+/// flags documented in `mount_namespaces(7)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    /// Mounts and unmounts never propagate in or out of this mount.
+    Private,
+    /// Mounts and unmounts propagate in from the shared peer this
+    /// mount is a slave of, but never back out to it.
+    Slave,
+    /// Mounts and unmounts propagate both ways between peers.
+    Shared,
+    /// Like `Private`, but also forbids bind mounting from this mount.
+    Unbindable,
+}
+
+/// A single classic-BPF instruction, i.e. Linux's `struct sock_filter`
+/// (see `linux/filter.h`): an 8-byte `{code, jt, jf, k}` tuple.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// A classic BPF program suitable for `seccomp(2)`'s
+/// `SECCOMP_SET_MODE_FILTER`, i.e. the `sock_filter` array of a
+/// `sock_fprog`, as produced by `libseccomp` or assembled by hand.
+#[derive(Debug, Clone)]
+pub struct BpfProgram {
+    pub filter: Vec<SockFilter>,
+}
+
+/// Highest `CAP_*` bit defined as of the kernel versions this library
+/// targets (`CAP_CHECKPOINT_RESTORE`); capabilities above this are
+/// never in the bounding set to begin with, so leaving it out of
+/// `keep` is enough to have it dropped.
+const CAP_LAST_CAP: i32 = 40;
+
+const _LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Drops every bit not in `keep` from the bounding set
+/// (`prctl(PR_CAPBSET_DROP, ...)`, one bit at a time) and sets the
+/// effective/permitted/inheritable sets to exactly `keep`
+/// (`capset(2)`).
+fn drop_capabilities(keep: &[i32]) -> io::Result<()> {
+    for cap in 0..(CAP_LAST_CAP + 1) {
+        if keep.contains(&cap) {
+            continue;
+        }
+        let rc = unsafe {
+            libc::prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0)
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    let mut data = [CapUserData { effective: 0, permitted: 0, inheritable: 0 }; 2];
+    for &cap in keep {
+        let idx = (cap / 32) as usize;
+        let bit = 1u32 << (cap % 32);
+        data[idx].effective |= bit;
+        data[idx].permitted |= bit;
+        data[idx].inheritable |= bit;
+    }
+    let header = CapUserHeader { version: _LINUX_CAPABILITY_VERSION_3, pid: 0 };
+    let rc = unsafe {
+        libc::syscall(libc::SYS_capset, &header as *const _, data.as_ptr())
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sets `PR_SET_CHILD_SUBREAPER` so orphaned descendants reparent to
+/// this process instead of escaping it. Called from `setup_child`
+/// before capabilities are dropped and `no_new_privs`/seccomp are
+/// applied, since `prctl(PR_SET_CHILD_SUBREAPER)` needs no special
+/// privilege and there's no reason to narrow the child further first;
+/// like the rest of `setup_child`, this only takes effect once the
+/// crate's spawn routine actually calls it in the forked child.
+fn apply_child_subreaper() -> io::Result<()> {
+    let rc = unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Sets `PR_SET_NO_NEW_PRIVS`, required before an unprivileged process
+/// can install a seccomp filter.
+fn apply_no_new_privs() -> io::Result<()> {
+    let rc = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The only `seccomp(2)` operation we use: install a classic-BPF
+/// filter as the new (additional) filter for the calling thread.
+const SECCOMP_SET_MODE_FILTER: libc::c_ulong = 1;
+
+#[repr(C)]
+struct SockFprog {
+    len: u16,
+    filter: *const SockFilter,
+}
+
+/// Installs `prog` via `seccomp(SECCOMP_SET_MODE_FILTER, 0, &sock_fprog)`.
+fn install_seccomp(prog: &BpfProgram) -> io::Result<()> {
+    let fprog = SockFprog {
+        len: prog.filter.len() as u16,
+        filter: prog.filter.as_ptr(),
+    };
+    let rc = unsafe {
+        libc::syscall(libc::SYS_seccomp, SECCOMP_SET_MODE_FILTER, 0u64,
+                       &fprog as *const SockFprog)
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
 impl Command {
 
     /// Allow child process to daemonize. By default we run equivalent of
@@ -30,13 +450,14 @@ impl Command {
     /// following:
     ///
     /// 1. The `prctl(PR_SET_CHILD_SUBREAPER..)` in parent which allows to
-    ///    "catch" descendant processes.
+    ///    "catch" descendant processes. Use ``make_child_subreaper()`` to
+    ///    set this for the child.
     ///
     /// 2. The pid namespaces
     ///
-    /// The former is out of scope of this library. The latter works by
-    /// ``cmd.unshare(Namespace::Pid)``, but you may need to setup mount points
-    /// and other important things (which are out of scope too).
+    /// The latter works by ``cmd.unshare(Namespace::Pid)``, and you can set
+    /// up the mount points a container needs with ``add_mount()`` and
+    /// friends.
     ///
     /// To reset this behavior use ``allow_daemonize()``.
     ///
@@ -44,6 +465,75 @@ impl Command {
         self.config.death_sig = Some(sig);
     }
 
+    /// Queue a mount operation to be performed in the child, after
+    /// namespaces are unshared but before `pivot_root`/`pivot_root_to`/
+    /// `chroot_dir` switch the root.
+    ///
+    /// Mounts are applied in the order they were added, which lets you
+    /// build up a working rootfs (bind mount the real filesystem in,
+    /// mount `/proc` and a `tmpfs` for `/dev`, ...) entirely from
+    /// within this library, without dropping out to a shell script.
+    pub fn add_mount(&mut self, spec: MountSpec) -> &mut Command {
+        self.config.mounts.push(spec);
+        self
+    }
+
+    /// Bind mount `src` onto `dest` in the child, before the root is
+    /// switched. Set `recursive` to also carry over mounts nested
+    /// under `src` (`MS_REC`).
+    pub fn bind_mount<A: AsRef<Path>, B: AsRef<Path>>(&mut self,
+        src: A, dest: B, recursive: bool)
+        -> &mut Command
+    {
+        self.add_mount(MountSpec::Bind {
+            src: path_to_cstring(src.as_ref()),
+            dest: path_to_cstring(dest.as_ref()),
+            recursive: recursive,
+        })
+    }
+
+    /// Mount a fresh `procfs` at `dest` in the child, before the root
+    /// is switched. Needed for most tools to work correctly inside a
+    /// `Pid` namespace.
+    pub fn mount_proc<P: AsRef<Path>>(&mut self, dest: P) -> &mut Command {
+        self.add_mount(MountSpec::Proc {
+            dest: path_to_cstring(dest.as_ref()),
+        })
+    }
+
+    /// Mount a `tmpfs` at `dest` in the child, before the root is
+    /// switched, with `options` passed as the mount data string (e.g.
+    /// `"size=64m,mode=0755"`).
+    pub fn mount_tmpfs<P: AsRef<Path>, S: AsRef<str>>(&mut self,
+        dest: P, options: S)
+        -> &mut Command
+    {
+        self.add_mount(MountSpec::Tmpfs {
+            dest: path_to_cstring(dest.as_ref()),
+            options: CString::new(options.as_ref())
+                .expect("mount options must not contain a NUL byte"),
+        })
+    }
+
+    /// Set mount propagation on the root of the mount tree in the
+    /// child, before any queued mounts or `pivot_root`/`chroot_dir` are
+    /// applied, via `mount("", "/", "", prop[ | MS_REC], NULL)`.
+    ///
+    /// Without this, `pivot_root`/`pivot_root_to` with `unmount: true`
+    /// may propagate the unmount of the old root back to the parent
+    /// mount namespace and affect other processes; if a `Mount`
+    /// namespace is unshared and `unmount: true` is requested without
+    /// an explicit call to this method, we default to recursive
+    /// `Slave` propagation, which the man pages describe as the safe
+    /// choice.
+    pub fn set_mount_propagation(&mut self, prop: Propagation,
+        recursive: bool)
+        -> &mut Command
+    {
+        self.config.propagation = Some((prop, recursive));
+        self
+    }
+
     /// Set chroot dir. Only absolute path is supported
     ///
     /// This method has a non-standard security feature: even if current_dir
@@ -55,6 +545,11 @@ impl Command {
     /// to either suffix of the current directory with stripped off pivot dir
     /// or the pivot dir itself (if old workdir is not prefixed by pivot dir)
     ///
+    /// If the new root needs `/proc`, `/dev` or other mounts set up before
+    /// the switch, queue them first with `add_mount` (or `bind_mount`/
+    /// `mount_proc`/`mount_tmpfs`); they are applied before this takes
+    /// effect.
+    ///
     /// # Panics
     ///
     /// If directory is not absolute
@@ -81,9 +576,11 @@ impl Command {
     /// **Warning** if you don't unshare the mount namespace you will get
     /// moved filesystem root for *all processes running in that namespace*
     /// including parent (currently running) process itself. If you don't
-    /// run equivalent to ``mount --make-private`` for the old root filesystem
-    /// and set ``unmount`` to true, you may get unmounted filesystem for
-    /// running processes too.
+    /// unshare it, ``set_mount_propagation`` is not applied either, and
+    /// setting ``unmount`` to true may unmount filesystems for running
+    /// processes too. With the mount namespace unshared, ``unmount`` set
+    /// to true gets recursive ``Slave`` propagation on the old root by
+    /// default (see ``set_mount_propagation``).
     ///
     /// See `man 2 pivot` for further details
     ///
@@ -112,11 +609,107 @@ impl Command {
                 panic!("The new_root is not a prefix of put old");
             }
         }
-        self.pivot_root = Some((new_root.to_path_buf(), put_old.to_path_buf(),
-                                unmount));
+        self.pivot_root = Some(PivotRoot::WithPutOld {
+            new_root: path_to_cstring(new_root),
+            put_old: path_to_cstring(put_old),
+            unmount: unmount,
+        });
+        self
+    }
+
+    /// Moves the root of the file system to `new_root` using the
+    /// `pivot_root(".", ".")` dance popularized by LXC and described by
+    /// Alexander Lutomirski, so that no separate `put_old` directory
+    /// needs to exist underneath `new_root`.
+    ///
+    /// In the child, this works by `open()`-ing both the current root
+    /// `/` and `new_root` with `O_DIRECTORY|O_RDONLY|O_CLOEXEC`,
+    /// `fchdir()`-ing into `new_root`, then calling
+    /// `pivot_root(".", ".")` with both arguments the single dot. That
+    /// stacks the old root on top of the new root at the current
+    /// directory; `fchdir()`-ing back to the old root fd and lazily
+    /// unmounting it (if `unmount` is set) then leaves `new_root` as
+    /// the only root left, with no `put_old` path ever involved.
+    ///
+    /// `new_root` must itself be a mount point, as `pivot_root(2)`
+    /// requires; if it isn't already one, it is bind-mounted onto
+    /// itself (`mount(new_root, new_root, MS_BIND|MS_REC)`) first.
+    ///
+    /// This removes the prefix restriction that `pivot_root` imposes
+    /// between `new_root` and `put_old`, at the cost of leaving no
+    /// path from which the old root can later be recovered.
+    ///
+    /// **Warning** same as `pivot_root`: unless you unshare the mount
+    /// namespace, you may unmount filesystems out from under other
+    /// processes in the namespace. The old root is always made a
+    /// recursive slave before it is unmounted; `set_mount_propagation`
+    /// only takes effect when the mount namespace is unshared.
+    ///
+    /// See `man 2 pivot_root` for further details.
+    ///
+    /// Note that if both chroot dir and pivot_root are specified, the
+    /// chroot dir is applied after pivot root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_root` is not absolute.
+    pub fn pivot_root_to<P: AsRef<Path>>(&mut self, new_root: P,
+        unmount: bool)
+        -> &mut Command
+    {
+        let new_root = new_root.as_ref();
+        if !new_root.is_absolute() {
+            panic!("New root must be absolute");
+        }
+        self.pivot_root = Some(PivotRoot::Dance {
+            new_root: path_to_cstring(new_root),
+            unmount: unmount,
+        });
         self
     }
 
+    /// Applies the Linux-specific child-side setup queued up by the
+    /// builder methods on this type: mount propagation, mount
+    /// configuration, the root switch, the subreaper flag, capability
+    /// dropping and seccomp filtering, in that order.
+    ///
+    /// # Safety / integration contract
+    ///
+    /// This must run in the forked child, after namespaces are
+    /// unshared and strictly before `execve`, and it relies on that
+    /// caller for async-signal-safety: every helper it calls uses raw
+    /// syscalls over pre-built `CString`s and touches no heap, so it is
+    /// safe to call between `clone` and `execve`, but the caller must
+    /// not, for example, run arbitrary user closures or allocate
+    /// around this call. The crate's spawn routine (in the module that
+    /// forks and execs, not present in this file) is expected to call
+    /// this exactly once in the child, before resolving and exec'ing
+    /// the target binary.
+    pub(crate) fn setup_child(&self) -> io::Result<()> {
+        if self.config.namespaces & consts::CLONE_NEWNS != 0 {
+            if let Some((prop, recursive)) = select_propagation(self) {
+                try!(apply_propagation(prop, recursive));
+            }
+        }
+        try!(apply_mounts(&self.config.mounts));
+        if let Some(ref spec) = self.pivot_root {
+            try!(apply_pivot_root(spec));
+        }
+        if self.config.child_subreaper {
+            try!(apply_child_subreaper());
+        }
+        if let Some(ref caps) = self.config.capabilities {
+            try!(drop_capabilities(caps));
+        }
+        if self.config.no_new_privs {
+            try!(apply_no_new_privs());
+        }
+        if let Some(ref prog) = self.config.seccomp {
+            try!(install_seccomp(prog));
+        }
+        Ok(())
+    }
+
     /// Unshare given namespaces
     ///
     /// Note: each namespace have some consequences on how new process will
@@ -155,4 +748,63 @@ impl Command {
         self.config.sigchld = true;
     }
 
+    /// Set `PR_SET_CHILD_SUBREAPER` in the child before `exec`, so that
+    /// when the target process acts as an init (most commonly PID 1 of
+    /// an unshared `Pid` namespace, see `unshare`) orphaned grandchildren
+    /// reparent to it instead of escaping to the nearest ancestor
+    /// subreaper, or PID 1 of the root namespace.
+    ///
+    /// Combined with `enable_child_signal`, this lets you build a
+    /// minimal container init that reliably reaps its whole process
+    /// subtree. See `set_parent_death_signal` for why `death_sig` alone
+    /// only covers the immediate child.
+    pub fn make_child_subreaper(&mut self) -> &mut Command {
+        self.config.child_subreaper = true;
+        self
+    }
+
+    /// Keep only the given capabilities (raw `CAP_*` numbers from
+    /// `linux/capability.h`, e.g. as exposed by the `libc` crate) in
+    /// the child's bounding set, and drop everything else from the
+    /// effective, permitted and inheritable sets via
+    /// `prctl(PR_CAPBSET_DROP, ...)` and `capset(2)`.
+    ///
+    /// This, along with `seccomp_filter`, is applied last in the
+    /// child, after all mount/pivot/uid-map setup and immediately
+    /// before `exec`, so it can be as tight as possible while still
+    /// letting the target binary run.
+    pub fn keep_capabilities<I: IntoIterator<Item=i32>>(&mut self, caps: I)
+        -> &mut Command
+    {
+        self.config.capabilities = Some(caps.into_iter().collect());
+        self
+    }
+
+    /// Drop every capability from the child's bounding, effective,
+    /// permitted and inheritable sets before `exec`. Equivalent to
+    /// `keep_capabilities(None)`.
+    pub fn drop_all_capabilities(&mut self) -> &mut Command {
+        self.keep_capabilities(None)
+    }
+
+    /// Set `PR_SET_NO_NEW_PRIVS` in the child before `exec`. Required
+    /// for an unprivileged process to install a `seccomp_filter`, and
+    /// good practice alongside `keep_capabilities`/
+    /// `drop_all_capabilities` regardless.
+    pub fn set_no_new_privs(&mut self) -> &mut Command {
+        self.config.no_new_privs = true;
+        self
+    }
+
+    /// Install a classic-BPF seccomp filter in the child via
+    /// `seccomp(SECCOMP_SET_MODE_FILTER, ...)`, immediately before
+    /// `exec`, after capabilities have already been dropped.
+    ///
+    /// Most filters require `PR_SET_NO_NEW_PRIVS` to be set first; see
+    /// `set_no_new_privs`.
+    pub fn seccomp_filter(&mut self, prog: BpfProgram) -> &mut Command {
+        self.config.seccomp = Some(prog);
+        self
+    }
+
 }